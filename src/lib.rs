@@ -32,53 +32,158 @@
 //! future and a close future so that you can shutdown the socket when its no longer required.
 //!
 //! With a turnstyle, you can join the queue every time you reload the configuration, and then
-//! share that future to all of the newly created listeners.  Once the new listeners are ready, you
-//! also do one turn on the turnstyle, which signals the last waiter in line -- a future shared
-//! with all of the old listeners -- that they can now shutdown.  That same turnstyle can perform
-//! this over and over without issue.
+//! share that future to all of the newly created listeners -- via `join_shared`, which hands back
+//! a cloneable `SharedWaiter` instead of a single-consumer `Waiter`.  Once the new listeners are
+//! ready, you also do one turn on the turnstyle, which signals the last waiter in line -- a future
+//! shared with all of the old listeners -- that they can now shutdown.  That same turnstyle can
+//! perform this over and over without issue.
 //!
 //! Turnstyles internally protect themselves via a `Mutex` but are fast enough in normal cases that
 //! you can `join` or `turn` from within a future without fear of stalling the executor.  If you're
 //! joining at an extremely high frequency, you could potentially cause performance degradation.
-extern crate futures;
-
-use futures::{prelude::*, sync::oneshot};
+//!
+//! `Waiter` is a `std::future::Future`, so it can be `.await`ed directly from an `async fn` running
+//! on any executor (tokio, async-std, etc).  `join` and `turn` themselves stay plain synchronous
+//! methods -- only the waiters they hand out are awaitable.  A `Waiter` resolves to `Some(msg)`
+//! once it's turned, or to `None` if every handle to its turnstyle is dropped first without ever
+//! reaching it.
+//!
+//! `Turnstyle<T>` is generic over the message a turn delivers.  `Turnstyle<usize>` -- the default
+//! type parameter, which `Turnstyle::new()` is inferred as once `turn()` is called on it or the
+//! binding is annotated as `Turnstyle` -- is the original auto-incrementing-version behavior;
+//! `turn_with` lets any `Turnstyle<T>` hand a caller-supplied value (a rebuilt listener set, a
+//! shutdown reason, a reload epoch) to exactly the next waiter in line.
+//!
+//! `turn_all` covers the other pattern the example above glosses over: once every new listener is
+//! up, you don't always want to release the old generation one at a time -- sometimes you want a
+//! single synchronization point that lets everyone still queued through at once.
+//!
+//! `len`/`is_empty` let a coordinator check how many participants are waiting before deciding
+//! whether it's worth turning at all, and `try_turn`/`try_turn_with` are non-blocking variants of
+//! `turn`/`turn_with` for callers (e.g. inside a latency-sensitive poll) that would rather skip a
+//! turn than stall on a contended lock.
+use futures::{channel::oneshot, future::Shared, task::AtomicWaker, FutureExt};
 use std::{
     collections::VecDeque,
-    sync::{Arc, Mutex, atomic::AtomicUsize, atomic::Ordering::SeqCst},
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::Ordering::SeqCst,
+        atomic::{AtomicU64, AtomicUsize},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
 };
 
+/// A single slot in a turnstyle's waiting queue: an id (so a dropped `Waiter` can find and remove
+/// its own entry) paired with the sender half it will be notified through.
+type Slot<T> = (u64, oneshot::Sender<T>);
+
+/// Error returned by [`Turnstyle::try_turn`]/[`Turnstyle::try_turn_with`] when the turnstyle's
+/// internal lock is currently held elsewhere.
+#[derive(Debug)]
+pub struct TryLockError(());
+
+impl fmt::Display for TryLockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "turnstyle lock is currently held by another thread")
+    }
+}
+
+impl std::error::Error for TryLockError {}
+
 /// A future that waits to be notified, based on its place in line.
-pub struct Waiter {
-    inner: oneshot::Receiver<usize>,
+///
+/// Resolves to `Some(msg)` once the turnstyle turns and reaches this waiter's position, or to
+/// `None` if every handle to the owning `Turnstyle` is dropped first, leaving this waiter's slot
+/// cleared without ever being turned.
+pub struct Waiter<T = usize> {
+    id: u64,
+    inner: oneshot::Receiver<T>,
+    waiters: Arc<Mutex<VecDeque<Slot<T>>>>,
+}
+
+impl<T> Future for Waiter<T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.get_mut().inner).poll(cx).map(Result::ok)
+    }
 }
 
-impl Future for Waiter {
-    type Error = ();
-    type Item = usize;
+impl<T> Drop for Waiter<T> {
+    fn drop(&mut self) {
+        // Remove our slot from the queue so a waiter that's dropped before being admitted doesn't
+        // linger as dead weight that `turn` has to skip past later.
+        let mut waiters = self.waiters.lock().unwrap();
+        waiters.retain(|(id, _)| *id != self.id);
+    }
+}
 
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> { self.inner.poll().map_err(|_| ()) }
+/// A cloneable version of [`Waiter`], for fanning a single turn out to many listeners.
+///
+/// Every clone resolves to the same position once the turnstyle turns, so a coordinator can hand
+/// one `join_shared` call's result to an entire generation of listeners -- e.g. every socket being
+/// torn down during a config reload -- instead of needing one waiter per listener.
+#[derive(Clone)]
+pub struct SharedWaiter<T = usize>
+where
+    T: Clone,
+{
+    inner: Shared<Waiter<T>>,
+}
+
+impl<T> Future for SharedWaiter<T>
+where
+    T: Clone,
+{
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.get_mut().inner).poll(cx)
+    }
 }
 
 /// An ordered queue of waiting participants.
 ///
 /// Every turn of the turnstyle, the next participant in queue is notified and removed from the
-/// queue.  If the queue is empty, `turn` is a noop.  Waiters receive their all-time position
-/// through the turnstyle as their item i.e. the first waiter receives 0, the second receives 1,
-/// etc.
+/// queue.  If the queue is empty, `turn`/`turn_with` is a noop.
+///
+/// `Turnstyle<T>` is generic over the message a turn delivers to its waiters; `T` defaults to
+/// `usize`, in which case waiters receive their all-time position through the turnstyle as their
+/// item i.e. the first waiter receives 0, the second receives 1, etc., and `turn()` auto-increments
+/// that version for you.  For any other `T`, use `turn_with` to deliver a specific value to the
+/// next waiter in line.
+///
+/// If every handle to a `Turnstyle` is dropped while waiters are still queued, those waiters
+/// resolve to `None` rather than being turned -- see [`Waiter`].
 ///
 /// Turnstyles can be cloned and are safe to share across threads.
-#[derive(Clone)]
-pub struct Turnstyle {
-    waiters: Arc<Mutex<VecDeque<oneshot::Sender<usize>>>>,
+pub struct Turnstyle<T = usize> {
+    waiters: Arc<Mutex<VecDeque<Slot<T>>>>,
+    next_id: Arc<AtomicU64>,
     version: Arc<AtomicUsize>,
 }
 
-impl Turnstyle {
+// Written by hand rather than derived: all of the fields are `Arc`s, so cloning a `Turnstyle`
+// never needs `T: Clone` even though `#[derive(Clone)]` would otherwise require it.
+impl<T> Clone for Turnstyle<T> {
+    fn clone(&self) -> Self {
+        Turnstyle {
+            waiters: Arc::clone(&self.waiters),
+            next_id: Arc::clone(&self.next_id),
+            version: Arc::clone(&self.version),
+        }
+    }
+}
+
+impl<T> Turnstyle<T> {
     /// Creates a new, empty turnstyle.
-    pub fn new() -> Turnstyle {
+    pub fn new() -> Turnstyle<T> {
         Turnstyle {
             waiters: Arc::new(Mutex::new(VecDeque::new())),
+            next_id: Arc::new(AtomicU64::new(0)),
             version: Arc::new(AtomicUsize::new(0)),
         }
     }
@@ -87,147 +192,533 @@ impl Turnstyle {
     ///
     /// Returns a `Waiter` to the caller, which will complete when the turnstyle turns and reaches
     /// the caller's position in the queue.
-    pub fn join(&self) -> Waiter {
+    pub fn join(&self) -> Waiter<T> {
         let (tx, rx) = oneshot::channel();
+        let id = self.next_id.fetch_add(1, SeqCst);
         {
             let mut waiters = self.waiters.lock().expect("turnstyle unable to join line");
-            waiters.push_back(tx);
+            waiters.push_back((id, tx));
+        }
+
+        Waiter {
+            id,
+            inner: rx,
+            waiters: Arc::clone(&self.waiters),
+        }
+    }
+
+    /// Joins the waiting queue, returning a cloneable waiter.
+    ///
+    /// Every clone of the returned `SharedWaiter` completes with the same position once the
+    /// turnstyle turns and reaches the caller's position in the queue, letting a single `join`
+    /// be shared across any number of listeners.
+    pub fn join_shared(&self) -> SharedWaiter<T>
+    where
+        T: Clone,
+    {
+        SharedWaiter {
+            inner: self.join().shared(),
+        }
+    }
+
+    /// Turns once, sending `msg` to a single waiter and letting it through.
+    ///
+    /// The `Waiter` is notified by the future completing with `msg`.  The function returns `true`
+    /// if a waiter was found/notified, `false` otherwise.
+    ///
+    /// A `Waiter` that was dropped before being admitted doesn't keep its slot around -- its own
+    /// `Drop` removes it from the queue -- but if one is closed out from under us between being
+    /// popped and being sent to, `turn_with` simply moves on to the next waiter in line rather than
+    /// panicking.
+    pub fn turn_with(&self, msg: T) -> bool {
+        let mut msg = msg;
+        loop {
+            let slot = {
+                let mut waiters = self.waiters.lock().unwrap();
+                waiters.pop_front()
+            };
+
+            match slot {
+                Some((_, tx)) => match tx.send(msg) {
+                    Ok(()) => return true,
+                    Err(rejected) => msg = rejected,
+                },
+                None => return false,
+            }
         }
+    }
 
-        Waiter { inner: rx }
+    /// Non-blocking version of `turn_with`, for use from latency-sensitive contexts that would
+    /// rather skip a turn than stall on a contended lock.
+    ///
+    /// Returns `Ok(bool)` with the same meaning as `turn_with`'s return value if the lock could be
+    /// acquired without blocking, or `Err(TryLockError)` if it's currently held elsewhere.
+    pub fn try_turn_with(&self, msg: T) -> Result<bool, TryLockError> {
+        let mut msg = msg;
+        loop {
+            let slot = {
+                let mut waiters = self.waiters.try_lock().map_err(|_| TryLockError(()))?;
+                waiters.pop_front()
+            };
+
+            match slot {
+                Some((_, tx)) => match tx.send(msg) {
+                    Ok(()) => return Ok(true),
+                    Err(rejected) => msg = rejected,
+                },
+                None => return Ok(false),
+            }
+        }
     }
 
-    /// Turns once, letting a single waiter through.
+    /// Returns the number of waiters currently queued.
+    pub fn len(&self) -> usize {
+        self.waiters.lock().unwrap().len()
+    }
+
+    /// Returns `true` if no waiters are currently queued.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Turnstyle<usize> {
+    /// Turns once, letting a single waiter through with the next sequential version number.
     ///
-    /// The `Waiter` is notified by the future completing.  The function returns `true` if a waiter
-    /// was found/notified, `false` otherwise.
+    /// This is the original `usize`-versioned behavior, kept as the default so that
+    /// `Turnstyle::new()` still works with a plain, argument-less `turn()`.  The version counter
+    /// only advances once a live waiter is actually found and notified -- calling `turn()` on an
+    /// empty queue (or one made up entirely of waiters that have since been dropped) is a true
+    /// no-op and doesn't burn a version number.
     pub fn turn(&self) -> bool {
-        let waiter = {
+        loop {
+            let slot = {
+                let mut waiters = self.waiters.lock().unwrap();
+                waiters.pop_front()
+            };
+
+            match slot {
+                Some((_, tx)) => {
+                    if tx.is_canceled() {
+                        continue;
+                    }
+
+                    let version = self.version.fetch_add(1, SeqCst);
+                    if tx.send(version).is_ok() {
+                        return true;
+                    }
+                }
+                None => return false,
+            }
+        }
+    }
+
+    /// Non-blocking version of `turn`, for use from latency-sensitive contexts that would rather
+    /// skip a turn than stall on a contended lock.
+    ///
+    /// Like `turn`, this only advances the version counter once a live waiter is actually about to
+    /// be notified.
+    pub fn try_turn(&self) -> Result<bool, TryLockError> {
+        loop {
+            let slot = {
+                let mut waiters = self.waiters.try_lock().map_err(|_| TryLockError(()))?;
+                waiters.pop_front()
+            };
+
+            match slot {
+                Some((_, tx)) => {
+                    if tx.is_canceled() {
+                        continue;
+                    }
+
+                    let version = self.version.fetch_add(1, SeqCst);
+                    if tx.send(version).is_ok() {
+                        return Ok(true);
+                    }
+                }
+                None => return Ok(false),
+            }
+        }
+    }
+
+    /// Turns the turnstyle for every waiter currently queued, in a single pass.
+    ///
+    /// Unlike `turn`, which admits one waiter per call, `turn_all` drains the whole queue at once
+    /// and assigns each currently-queued waiter its sequential version, so a coordinator can
+    /// release an entire generation of listeners together instead of needing to call `turn` once
+    /// per waiter.  Returns the number of waiters that were actually released; waiters that had
+    /// already been dropped are skipped and don't count towards it.
+    pub fn turn_all(&self) -> usize {
+        let drained = {
             let mut waiters = self.waiters.lock().unwrap();
-            waiters.pop_front()
+            std::mem::take(&mut *waiters)
         };
 
-        if let Some(w) = waiter {
+        let mut released = 0;
+        for (_, tx) in drained {
+            if tx.is_canceled() {
+                continue;
+            }
+
             let version = self.version.fetch_add(1, SeqCst);
-            w.send(version).expect("turnstyle failed to signal next in line");
-            true
-        } else {
-            false
+            if tx.send(version).is_ok() {
+                released += 1;
+            }
         }
+        released
     }
 }
 
-impl Drop for Turnstyle {
+impl<T> Drop for Turnstyle<T> {
     fn drop(&mut self) {
-        while self.turn() {}
+        // Waiters still queued when the last handle to this turnstyle goes away will never be
+        // turned; drop their senders so each resolves to `None` on its next poll instead of
+        // hanging forever.
+        self.waiters.lock().unwrap().clear();
+    }
+}
+
+/// A lock-free, broadcast-latest alternative to [`Turnstyle`].
+///
+/// `Turnstyle` gives every waiter a strict FIFO position, but paying for a `Mutex<VecDeque>` on
+/// every `join`/`turn` can matter if you're doing that at very high frequency.  `WatchTurnstyle`
+/// trades the FIFO queue for a single `AtomicUsize` generation counter: `join` just records the
+/// current generation, and `turn` is a single `fetch_add` plus waking whichever waiter is
+/// currently registered -- there's no queue to lock at all.
+///
+/// The cost of going lock-free this way is that `WatchTurnstyle` only reliably wakes the most
+/// recently polled-while-pending waiter; it's meant for the case where you have (at most) one task
+/// actively waiting on a given instant, not for fanning a turn out to many listeners at once.  Use
+/// [`Turnstyle::join_shared`] for that; reach for `WatchTurnstyle` only when you've established
+/// that the `Mutex` in `Turnstyle` is actually showing up as a bottleneck.
+#[derive(Clone)]
+pub struct WatchTurnstyle {
+    generation: Arc<AtomicUsize>,
+    waker: Arc<AtomicWaker>,
+}
+
+impl WatchTurnstyle {
+    /// Creates a new watch turnstyle at generation zero.
+    pub fn new() -> WatchTurnstyle {
+        WatchTurnstyle {
+            generation: Arc::new(AtomicUsize::new(0)),
+            waker: Arc::new(AtomicWaker::new()),
+        }
+    }
+
+    /// Joins the turnstyle, recording the current generation as this waiter's starting point.
+    ///
+    /// The returned `WatchWaiter` resolves as soon as a `turn` advances the generation past the
+    /// point it joined at, yielding how many turns it took.
+    pub fn join(&self) -> WatchWaiter {
+        WatchWaiter {
+            generation: Arc::clone(&self.generation),
+            waker: Arc::clone(&self.waker),
+            joined_at: self.generation.load(SeqCst),
+        }
+    }
+
+    /// Turns once, advancing the generation counter and waking the currently registered waiter.
+    ///
+    /// Returns the new generation.  Unlike `Turnstyle::turn`, this never touches a lock.
+    pub fn turn(&self) -> usize {
+        let generation = self.generation.fetch_add(1, SeqCst) + 1;
+        self.waker.wake();
+        generation
+    }
+}
+
+/// A future returned by [`WatchTurnstyle::join`].
+///
+/// Resolves once the turnstyle has turned past the generation it was joined at, yielding the
+/// number of turns that have happened since.
+pub struct WatchWaiter {
+    generation: Arc<AtomicUsize>,
+    waker: Arc<AtomicWaker>,
+    joined_at: usize,
+}
+
+impl Future for WatchWaiter {
+    type Output = usize;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        let elapsed = this.generation.load(SeqCst).wrapping_sub(this.joined_at);
+        if elapsed > 0 {
+            return Poll::Ready(elapsed);
+        }
+
+        // Register before the final check so a `turn` that lands between our first load and this
+        // registration still wakes us, rather than being missed entirely.
+        this.waker.register(cx.waker());
+
+        let elapsed = this.generation.load(SeqCst).wrapping_sub(this.joined_at);
+        if elapsed > 0 {
+            Poll::Ready(elapsed)
+        } else {
+            Poll::Pending
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Turnstyle;
-    use futures::{future, Future, Async};
+    use super::{Turnstyle, WatchTurnstyle};
+    use futures::task::noop_waker_ref;
+    use std::{
+        future::Future,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    fn poll<F: Future + Unpin>(f: &mut F) -> Poll<F::Output> {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        Pin::new(f).poll(&mut cx)
+    }
 
     #[test]
     fn single_waiter() {
-        future::lazy(|| {
-            let ts = Turnstyle::new();
+        let ts = Turnstyle::new();
 
-            let mut w = ts.join();
-            assert!(!w.poll().unwrap().is_ready());
+        let mut w = ts.join();
+        assert!(poll(&mut w).is_pending());
 
-            ts.turn();
-            assert!(w.poll().unwrap().is_ready());
-
-            future::ok::<_, ()>(())
-        }).wait()
-            .unwrap();
+        ts.turn();
+        assert!(poll(&mut w).is_ready());
     }
 
     #[test]
     fn multiple_waiters() {
-        future::lazy(|| {
-            let ts = Turnstyle::new();
-            let mut w1 = ts.join();
-            let mut w2 = ts.join();
-            let mut w3 = ts.join();
+        let ts = Turnstyle::new();
+        let mut w1 = ts.join();
+        let mut w2 = ts.join();
+        let mut w3 = ts.join();
+
+        assert!(poll(&mut w1).is_pending());
+        assert!(poll(&mut w2).is_pending());
+        assert!(poll(&mut w3).is_pending());
+
+        ts.turn();
+        assert!(poll(&mut w1).is_ready());
+        assert!(poll(&mut w2).is_pending());
+        assert!(poll(&mut w3).is_pending());
+
+        ts.turn();
+        assert!(poll(&mut w2).is_ready());
+        assert!(poll(&mut w3).is_pending());
+
+        ts.turn();
+        assert!(poll(&mut w3).is_ready());
+    }
 
-            assert!(!w1.poll().unwrap().is_ready());
-            assert!(!w2.poll().unwrap().is_ready());
-            assert!(!w2.poll().unwrap().is_ready());
+    #[test]
+    fn shared_waiter_fans_out_to_all_clones() {
+        let ts = Turnstyle::new();
 
-            ts.turn();
-            assert!(w1.poll().unwrap().is_ready());
-            assert!(!w2.poll().unwrap().is_ready());
-            assert!(!w3.poll().unwrap().is_ready());
+        let mut shared1 = ts.join_shared();
+        let mut shared2 = shared1.clone();
+        let mut shared3 = shared1.clone();
 
-            ts.turn();
-            assert!(w2.poll().unwrap().is_ready());
-            assert!(!w3.poll().unwrap().is_ready());
+        assert!(poll(&mut shared1).is_pending());
+        assert!(poll(&mut shared2).is_pending());
+        assert!(poll(&mut shared3).is_pending());
 
-            ts.turn();
-            assert!(w3.poll().unwrap().is_ready());
+        ts.turn();
 
-            future::ok::<_, ()>(())
-        }).wait()
-            .unwrap();
+        match (poll(&mut shared1), poll(&mut shared2), poll(&mut shared3)) {
+            (Poll::Ready(v1), Poll::Ready(v2), Poll::Ready(v3)) => {
+                assert_eq!(v1, Some(0));
+                assert_eq!(v2, Some(0));
+                assert_eq!(v3, Some(0));
+            }
+            _ => panic!("all clones of a shared waiter should be ready together"),
+        }
+    }
+
+    #[test]
+    fn turn_skips_dropped_waiters() {
+        let ts = Turnstyle::new();
+
+        let w1 = ts.join();
+        let mut w2 = ts.join();
+
+        drop(w1);
+
+        assert!(ts.turn());
+        assert!(poll(&mut w2).is_ready());
+        assert!(!ts.turn());
     }
 
     #[test]
     fn versions() {
-        future::lazy(|| {
-            let ts = Turnstyle::new();
-            let mut w1 = ts.join();
-            let mut w2 = ts.join();
-            let mut w3 = ts.join();
-
-            ts.turn();
-            ts.turn();
-            ts.turn();
-
-            if let Async::Ready(w1v) = w1.poll().unwrap() {
-                assert_eq!(w1v, 0);
-            } else {
-                panic!("waiter 1 was not ready");
-            }
+        let ts = Turnstyle::new();
+        let mut w1 = ts.join();
+        let mut w2 = ts.join();
+        let mut w3 = ts.join();
+
+        ts.turn();
+        ts.turn();
+        ts.turn();
+
+        match poll(&mut w1) {
+            Poll::Ready(v) => assert_eq!(v, Some(0)),
+            Poll::Pending => panic!("waiter 1 was not ready"),
+        }
 
-            if let Async::Ready(w2v) = w2.poll().unwrap() {
-                assert_eq!(w2v, 1);
-            } else {
-                panic!("waiter 2 was not ready");
-            }
+        match poll(&mut w2) {
+            Poll::Ready(v) => assert_eq!(v, Some(1)),
+            Poll::Pending => panic!("waiter 2 was not ready"),
+        }
 
-            if let Async::Ready(w3v) = w3.poll().unwrap() {
-                assert_eq!(w3v, 2);
-            } else {
-                panic!("waiter 3 was not ready");
-            }
+        match poll(&mut w3) {
+            Poll::Ready(v) => assert_eq!(v, Some(2)),
+            Poll::Pending => panic!("waiter 3 was not ready"),
+        }
+    }
+
+    #[test]
+    fn turn_all_releases_whole_queue_at_once() {
+        let ts = Turnstyle::new();
 
-            future::ok::<_, ()>(())
-        }).wait()
-            .unwrap();
+        let mut w1 = ts.join();
+        let mut w2 = ts.join();
+        let mut w3 = ts.join();
+
+        assert_eq!(ts.turn_all(), 3);
+
+        match poll(&mut w1) {
+            Poll::Ready(v) => assert_eq!(v, Some(0)),
+            Poll::Pending => panic!("waiter 1 was not ready"),
+        }
+
+        match poll(&mut w2) {
+            Poll::Ready(v) => assert_eq!(v, Some(1)),
+            Poll::Pending => panic!("waiter 2 was not ready"),
+        }
+
+        match poll(&mut w3) {
+            Poll::Ready(v) => assert_eq!(v, Some(2)),
+            Poll::Pending => panic!("waiter 3 was not ready"),
+        }
+
+        assert_eq!(ts.turn_all(), 0);
+    }
+
+    #[test]
+    fn turn_all_skips_canceled_slots_without_wasting_versions() {
+        let ts = Turnstyle::new();
+
+        let mut w1 = ts.join();
+        let mut w3 = ts.join();
+
+        // Splice in a slot whose receiver has already been dropped, simulating the race between
+        // `turn_all`'s queue snapshot and a concurrent `Waiter::drop` that loses the race to pull
+        // its own entry out of the queue first.
+        {
+            let (tx, rx) = futures::channel::oneshot::channel::<usize>();
+            drop(rx);
+            ts.waiters.lock().unwrap().insert(1, (u64::MAX, tx));
+        }
+
+        assert_eq!(ts.turn_all(), 2);
+
+        match poll(&mut w1) {
+            Poll::Ready(v) => assert_eq!(v, Some(0)),
+            Poll::Pending => panic!("waiter 1 was not ready"),
+        }
+
+        match poll(&mut w3) {
+            Poll::Ready(v) => assert_eq!(v, Some(1)),
+            Poll::Pending => panic!("waiter 3 was not ready"),
+        }
+    }
+
+    #[test]
+    fn turn_with_delivers_custom_payload() {
+        let ts: Turnstyle<&'static str> = Turnstyle::new();
+
+        let mut w1 = ts.join();
+        let mut w2 = ts.join();
+
+        assert!(ts.turn_with("reloading"));
+        match poll(&mut w1) {
+            Poll::Ready(msg) => assert_eq!(msg, Some("reloading")),
+            Poll::Pending => panic!("waiter 1 was not ready"),
+        }
+        assert!(poll(&mut w2).is_pending());
+
+        assert!(ts.turn_with("shutdown"));
+        match poll(&mut w2) {
+            Poll::Ready(msg) => assert_eq!(msg, Some("shutdown")),
+            Poll::Pending => panic!("waiter 2 was not ready"),
+        }
     }
 
     #[test]
     fn on_drop() {
-        future::lazy(|| {
-            let ts = Turnstyle::new();
-            let mut w1 = ts.join();
-            let mut w2 = ts.join();
-            let mut w3 = ts.join();
+        let ts: Turnstyle<usize> = Turnstyle::new();
+        let mut w1 = ts.join();
+        let mut w2 = ts.join();
+        let mut w3 = ts.join();
+
+        assert!(poll(&mut w1).is_pending());
+        assert!(poll(&mut w2).is_pending());
+        assert!(poll(&mut w3).is_pending());
+
+        drop(ts);
+
+        assert_eq!(poll(&mut w1), Poll::Ready(None));
+        assert_eq!(poll(&mut w2), Poll::Ready(None));
+        assert_eq!(poll(&mut w3), Poll::Ready(None));
+    }
+
+    #[test]
+    fn watch_turnstyle_resolves_after_turn() {
+        let ts = WatchTurnstyle::new();
+
+        let mut w = ts.join();
+        assert!(poll(&mut w).is_pending());
 
-            assert!(!w1.poll().unwrap().is_ready());
-            assert!(!w2.poll().unwrap().is_ready());
-            assert!(!w2.poll().unwrap().is_ready());
+        ts.turn();
+        assert_eq!(poll(&mut w), Poll::Ready(1));
+    }
+
+    #[test]
+    fn watch_turnstyle_reports_turns_since_join() {
+        let ts = WatchTurnstyle::new();
+
+        let mut w = ts.join();
+
+        ts.turn();
+        ts.turn();
+        ts.turn();
 
-            drop(ts);
+        assert_eq!(poll(&mut w), Poll::Ready(3));
+    }
 
-            assert!(w1.poll().unwrap().is_ready());
-            assert!(w2.poll().unwrap().is_ready());
-            assert!(w3.poll().unwrap().is_ready());
+    #[test]
+    fn len_and_is_empty_track_the_queue() {
+        let ts = Turnstyle::new();
+        assert!(ts.is_empty());
+        assert_eq!(ts.len(), 0);
+
+        let _w1 = ts.join();
+        let _w2 = ts.join();
+        assert!(!ts.is_empty());
+        assert_eq!(ts.len(), 2);
+
+        ts.turn();
+        assert_eq!(ts.len(), 1);
+    }
+
+    #[test]
+    fn try_turn_admits_a_waiter_when_uncontended() {
+        let ts = Turnstyle::new();
+        let mut w = ts.join();
 
-            future::ok::<_, ()>(())
-        }).wait()
-            .unwrap();
+        assert!(ts.try_turn().unwrap());
+        assert!(poll(&mut w).is_ready());
+        assert!(!ts.try_turn().unwrap());
     }
 }